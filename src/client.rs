@@ -2,31 +2,260 @@
 use crate::messages::*;
 use bincode::Options;
 use log::error;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
+use std::collections::HashSet;
 use std::io;
-use std::io::{Error, ErrorKind};
+use std::io::{Error, ErrorKind, Read, Write};
 use std::os::unix::net::UnixStream;
-use std::sync::mpsc::{channel, Sender};
+use std::sync::mpsc::{channel, Receiver, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
 
+// Bumped whenever the wire format of `Hello`/`HelloAck`, `ToDaemon` or
+// `FromDaemon` changes in a way that isn't backward compatible.
+const PROTOCOL_VERSION: u32 = 1;
+
+// How long `handshake` waits for a `HelloAck` before giving up on it.
+// Capability negotiation is meant to degrade gracefully, not to be a
+// required preamble: a daemon predating it will never reply to `Hello`, and
+// a new client must still be able to talk to that old daemon rather than
+// hang or fail to connect.
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_millis(500);
+
+// How many consecutive corrupt frames `get_next_message`/the reader loop
+// will discard and resync past before giving up and treating the stream as
+// genuinely broken.
+const MAX_CONSECUTIVE_DECODE_ERRORS: u32 = 5;
+
+// No real `Request`/`Response` payload comes close to this. A length prefix
+// above it is corrupt and must be rejected as a decode error rather than
+// buffered: an unbounded `len` would otherwise make `FrameReader` read
+// forever without ever completing a frame, buffering unbounded memory and
+// never tripping `MAX_CONSECUTIVE_DECODE_ERRORS`.
+const MAX_FRAME_LEN: usize = 1024 * 1024;
+
+// Writes `value` as a length-delimited bincode frame: a `u32` byte length
+// followed by the payload. Framing lets a reader skip a corrupt or partial
+// message by its declared length instead of desyncing the whole stream.
+fn write_frame<T: Serialize>(mut stream: &UnixStream, value: &T) -> io::Result<()> {
+    let config = bincode::DefaultOptions::new().with_native_endian();
+    let payload = config
+        .serialize(value)
+        .map_err(|_| Error::new(ErrorKind::Other, "bincode error"))?;
+
+    stream.write_all(&(payload.len() as u32).to_le_bytes())?;
+    stream.write_all(&payload)?;
+    Ok(())
+}
+
+// Reads and decodes length-delimited frames off a stream, buffering
+// whatever bytes have already arrived for a frame that isn't complete yet.
+// `read_exact` would be simpler, but it discards any bytes it already read
+// when interrupted midway through a frame by a `WouldBlock`/`TimedOut` (from
+// a read timeout) — losing them desyncs every frame read after it. Keeping
+// the partial frame in `buf` across calls means a later call just resumes
+// filling it in instead of losing its place in the stream.
+struct FrameReader {
+    buf: Vec<u8>,
+}
+
+impl FrameReader {
+    fn new() -> Self {
+        Self { buf: Vec::new() }
+    }
+
+    // Returns the I/O error as-is on a short read (the caller decides
+    // whether that means the stream is dead or just timed out); a
+    // successfully-read frame that fails to decode is reported via
+    // `Ok(Err(_))` so the caller can discard it and keep reading at the next
+    // frame boundary rather than losing its place in the stream.
+    fn read_frame<T: DeserializeOwned>(
+        &mut self,
+        mut stream: &UnixStream,
+    ) -> io::Result<Result<T, ()>> {
+        let mut chunk = [0u8; 4096];
+
+        loop {
+            if self.buf.len() >= 4 {
+                let len = u32::from_le_bytes(self.buf[0..4].try_into().unwrap()) as usize;
+                if len > MAX_FRAME_LEN {
+                    // The length prefix itself is what's corrupt, so there's
+                    // no real frame boundary to skip to; drop just those 4
+                    // bytes and let the caller's decode-error budget resync
+                    // from whatever follows.
+                    self.buf.drain(0..4);
+                    return Ok(Err(()));
+                }
+                if self.buf.len() >= 4 + len {
+                    let payload: Vec<u8> = self.buf.drain(0..4 + len).skip(4).collect();
+                    let config = bincode::DefaultOptions::new().with_native_endian();
+                    return Ok(config.deserialize(&payload).map_err(|_| ()));
+                }
+            }
+
+            let n = stream.read(&mut chunk)?;
+            if n == 0 {
+                return Err(Error::new(ErrorKind::UnexpectedEof, "connection closed"));
+            }
+            self.buf.extend_from_slice(&chunk[..n]);
+        }
+    }
+}
+
+fn is_timeout(err: &io::Error) -> bool {
+    matches!(err.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut)
+}
+
+// A request-kind tag without its payload, so `Hello`/`HelloAck` can advertise
+// which `Request` variants a peer understands without shipping sample
+// payloads over the wire.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum RequestKind {
+    SetBrightness,
+    GetBrightness,
+    EnableScreen,
+    DisableScreen,
+    Reboot,
+    PowerOff,
+    Subscribe,
+}
+
+impl RequestKind {
+    fn of(request: &Request) -> Self {
+        match request {
+            Request::SetBrightness(_) => RequestKind::SetBrightness,
+            Request::GetBrightness => RequestKind::GetBrightness,
+            Request::EnableScreen(_) => RequestKind::EnableScreen,
+            Request::DisableScreen(_) => RequestKind::DisableScreen,
+            Request::Reboot => RequestKind::Reboot,
+            Request::PowerOff => RequestKind::PowerOff,
+            Request::Subscribe(_) => RequestKind::Subscribe,
+        }
+    }
+
+    fn all() -> Vec<RequestKind> {
+        vec![
+            RequestKind::SetBrightness,
+            RequestKind::GetBrightness,
+            RequestKind::EnableScreen,
+            RequestKind::DisableScreen,
+            RequestKind::Reboot,
+            RequestKind::PowerOff,
+            RequestKind::Subscribe,
+        ]
+    }
+}
+
+// The kinds of unsolicited notification a client can ask the daemon to push,
+// via `Request::Subscribe`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum EventKind {
+    BrightnessChanged,
+    PowerButtonPressed,
+    LidClosed,
+    LidOpened,
+    AcPlugged,
+    AcUnplugged,
+}
+
+// An unsolicited notification pushed by the daemon to a subscribed client.
+// Carried over the same `FromDaemon`/`Response` wire types as an ordinary
+// reply; what makes it an event rather than a reply is that the client never
+// deregisters its listener after receiving one.
+pub type Event = Response;
+
+// Sent by a client right after connecting, advertising the protocol version
+// and the set of `Request` variants it knows how to build.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Hello {
+    version: u32,
+    supported: Vec<RequestKind>,
+}
+
+// The daemon's reply to `Hello`, advertising its own version and the
+// `Request` variants it knows how to handle.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct HelloAck {
+    version: u32,
+    supported: Vec<RequestKind>,
+}
+
+#[derive(Debug)]
 pub enum HalError {
     StreamError,
     NoListener,
+    // The daemon never advertised support for this request during the
+    // connect handshake, so it was never sent on the wire.
+    Unsupported,
+    // No reply arrived within the requested timeout; the abandoned req_id's
+    // listener has already been removed from `listeners`.
+    Timeout,
+}
+
+// Governs how `HalClient` re-establishes the connection after the daemon
+// restarts or the socket otherwise drops out from under it.
+#[derive(Debug, Clone, Copy)]
+pub struct ReconnectPolicy {
+    pub max_retries: u32,
+    pub initial_backoff: Duration,
+    pub max_backoff: Duration,
+}
+
+impl Default for ReconnectPolicy {
+    fn default() -> Self {
+        Self {
+            max_retries: 5,
+            initial_backoff: Duration::from_millis(100),
+            max_backoff: Duration::from_secs(5),
+        }
+    }
 }
 
 pub struct HalClient {
+    path: String,
     stream: UnixStream,
     req_id: u64,
-    listeners: HashMap<u64, Sender<Response>>,
+    // Keeps the original `Request` alongside its listener so a dropped
+    // connection can replay it under its original id once reconnected.
+    listeners: HashMap<u64, (Request, Sender<Response>)>,
+    // `req_id`s registered via `Request::Subscribe`, whose listener must stay
+    // registered across multiple events instead of being removed after one.
+    subscriptions: HashSet<u64>,
+    // Populated from the daemon's `HelloAck` during `connect`.
+    daemon_supports: HashSet<RequestKind>,
+    policy: ReconnectPolicy,
+    // Holds any bytes read for a frame that wasn't complete yet, so a read
+    // interrupted by `get_next_message_with_timeout`'s read timeout resumes
+    // where it left off instead of desyncing the stream.
+    reader: FrameReader,
+    // The deadline `get_next_message_with_timeout` is currently waiting
+    // against, if any. `reconnect` consults this to re-apply however much
+    // of that deadline is actually left to the fresh stream, rather than
+    // the stale full timeout duration the old stream had.
+    active_deadline: Option<Instant>,
 }
 
 impl HalClient {
-    pub fn connect(path: &str) -> Result<Self, io::Error> {
+    pub fn connect(path: &str, policy: ReconnectPolicy) -> Result<Self, io::Error> {
         match UnixStream::connect(path) {
-            Ok(stream) => Ok(Self {
-                stream,
-                req_id: 0,
-                listeners: HashMap::new(),
-            }),
+            Ok(stream) => {
+                let mut reader = FrameReader::new();
+                let daemon_supports = Self::handshake(&stream, &mut reader);
+                Ok(Self {
+                    path: path.to_string(),
+                    stream,
+                    req_id: 0,
+                    listeners: HashMap::new(),
+                    subscriptions: HashSet::new(),
+                    daemon_supports,
+                    policy,
+                    reader,
+                    active_deadline: None,
+                })
+            }
             Err(err) => {
                 error!("Failed to connect to b2ghald at {}: {}", path, err);
                 Err(err)
@@ -34,37 +263,432 @@ impl HalClient {
         }
     }
 
-    pub fn send(&mut self, request: Request, sender: Sender<Response>) -> Result<(), io::Error> {
+    // On a stream error, tries to re-establish the connection to `self.path`
+    // with capped exponential backoff and replay every still-pending
+    // request under its original req_id. If every attempt is exhausted,
+    // every pending listener is dropped so callers unblock deterministically
+    // with a `RecvError` instead of hanging forever.
+    fn reconnect(&mut self) -> Result<(), HalError> {
+        let mut backoff = self.policy.initial_backoff;
+
+        for attempt in 1..=self.policy.max_retries {
+            thread::sleep(backoff);
+
+            match UnixStream::connect(&self.path) {
+                Ok(stream) => {
+                    let mut reader = FrameReader::new();
+                    let daemon_supports = Self::handshake(&stream, &mut reader);
+
+                    // `get_next_message_with_timeout` puts a read timeout on
+                    // `self.stream` for the duration of its deadline (tracked
+                    // in `active_deadline`); this fresh `UnixStream` has no
+                    // timeout of its own, which would silently turn the rest
+                    // of that deadline into a blocking read. Re-apply however
+                    // much of the deadline is actually still left, computed
+                    // now rather than before backoff/connect/handshake spent
+                    // their own time — a stale duration would let a
+                    // reconnect stretch the caller's timeout by however long
+                    // the reconnect itself took. If the deadline has already
+                    // elapsed, don't hand the new stream a zero timeout (that
+                    // fails and leaves it blocking indefinitely, since
+                    // `set_read_timeout` rejects zero durations); note it so
+                    // this call reports the timeout, but still adopt the new
+                    // connection and replay every other pending request below
+                    // rather than abandoning them.
+                    let mut deadline_elapsed = false;
+                    if let Some(deadline) = self.active_deadline {
+                        let remaining = deadline.saturating_duration_since(Instant::now());
+                        if remaining.is_zero() {
+                            error!("Deadline elapsed while reconnecting.");
+                            deadline_elapsed = true;
+                        } else {
+                            let _ = stream.set_read_timeout(Some(remaining));
+                        }
+                    }
+
+                    self.stream = stream;
+                    self.daemon_supports = daemon_supports;
+                    self.reader = reader;
+                    self.replay_pending();
+
+                    if deadline_elapsed {
+                        return Err(HalError::Timeout);
+                    }
+                    return Ok(());
+                }
+                Err(err) => {
+                    error!(
+                        "Reconnect attempt {}/{} to {} failed: {}",
+                        attempt, self.policy.max_retries, self.path, err
+                    );
+                }
+            }
+
+            backoff = (backoff * 2).min(self.policy.max_backoff);
+        }
+
+        error!(
+            "Exhausted {} reconnect attempts to {}, failing pending requests.",
+            self.policy.max_retries, self.path
+        );
+        self.listeners.clear();
+        self.subscriptions.clear();
+        Err(HalError::StreamError)
+    }
+
+    // Re-sends every request that was still awaiting a reply when the
+    // connection dropped, each under its original `req_id` — not a fresh
+    // one. A caller waiting in `get_next_message_with_timeout(req_id, ...)`
+    // only ever matches that specific id; reassigning a new one here would
+    // mean the real reply could never satisfy it, and the call would report
+    // a spurious timeout even once the reply actually arrived.
+    fn replay_pending(&mut self) {
+        let pending: Vec<_> = self.listeners.drain().collect();
+        self.subscriptions.clear();
+
+        for (id, (request, sender)) in pending {
+            if !self.daemon_supports.contains(&RequestKind::of(&request)) {
+                error!("Dropping a pending request the reconnected daemon no longer supports.");
+                continue;
+            }
+
+            if matches!(request, Request::Subscribe(_)) {
+                self.subscriptions.insert(id);
+            }
+            let message = ToDaemon::new(id, request.clone());
+            self.listeners.insert(id, (request, sender));
+
+            if let Err(err) = write_frame(&self.stream, &message) {
+                error!("Failed to replay a pending request after reconnecting: {}", err);
+                self.listeners.remove(&id);
+            }
+        }
+    }
+
+    // Exchanges `Hello`/`HelloAck` with the daemon and returns the set of
+    // `Request` variants it advertised support for. `reader` is the same
+    // `FrameReader` the caller keeps using afterwards (`self.reader`, or the
+    // reader thread's), so any bytes read past the `HelloAck` boundary stay
+    // buffered for it instead of being discarded by a throwaway reader.
+    //
+    // A daemon that predates capability negotiation will never send a
+    // `HelloAck`, so this never hard-fails on a missing or malformed reply:
+    // past `HANDSHAKE_TIMEOUT`, or on any handshake error, it logs and falls
+    // back to assuming the daemon is such a legacy build and supports every
+    // `Request` variant.
+    fn handshake(stream: &UnixStream, reader: &mut FrameReader) -> HashSet<RequestKind> {
+        let legacy_supports = || RequestKind::all().into_iter().collect();
+
+        let hello = Hello {
+            version: PROTOCOL_VERSION,
+            supported: RequestKind::all(),
+        };
+        if let Err(err) = write_frame(stream, &hello) {
+            error!("Failed to send Hello, assuming a legacy daemon: {}", err);
+            return legacy_supports();
+        }
+
+        let _ = stream.set_read_timeout(Some(HANDSHAKE_TIMEOUT));
+        let ack = reader.read_frame::<HelloAck>(stream);
+        let _ = stream.set_read_timeout(None);
+
+        match ack {
+            Ok(Ok(ack)) => ack.supported.into_iter().collect(),
+            Ok(Err(())) => {
+                error!("Received a malformed HelloAck, assuming a legacy daemon.");
+                legacy_supports()
+            }
+            Err(err) => {
+                error!(
+                    "No HelloAck within {:?} ({}), assuming a legacy daemon.",
+                    HANDSHAKE_TIMEOUT, err
+                );
+                legacy_supports()
+            }
+        }
+    }
+
+    // Sends `request`, returning the `req_id` it was assigned so the caller
+    // can later abandon it (see `get_next_message_with_timeout`).
+    pub fn send(&mut self, request: Request, sender: Sender<Response>) -> Result<u64, HalError> {
+        if !self.daemon_supports.contains(&RequestKind::of(&request)) {
+            return Err(HalError::Unsupported);
+        }
+
         let id = self.req_id;
         self.req_id += 1;
-        let message = ToDaemon::new(id, request);
-        self.listeners.insert(id, sender);
+        if matches!(request, Request::Subscribe(_)) {
+            self.subscriptions.insert(id);
+        }
+        let message = ToDaemon::new(id, request.clone());
+        self.listeners.insert(id, (request, sender));
 
-        let config = bincode::DefaultOptions::new().with_native_endian();
+        write_frame(&self.stream, &message).map_err(|_| HalError::StreamError)?;
 
-        config
-            .serialize_into(&self.stream, &message)
-            .map_err(|_| Error::new(ErrorKind::Other, "bincode error"))?;
+        Ok(id)
+    }
 
-        Ok(())
+    // Blocks to get the next message, dispatches it to the registered
+    // listener, and returns the `req_id` it was dispatched for — a
+    // `HalClient` with more than one in-flight request can have several
+    // `req_id`s waiting at once, and the caller (see
+    // `get_next_message_with_timeout`) needs to know whether the message it
+    // just read was the one it's actually waiting for.
+    //
+    // A subscription's listener stays registered so it keeps receiving
+    // every event; any other listener is removed after its reply. A frame
+    // that fails to decode is discarded and the next frame is read instead,
+    // up to `MAX_CONSECUTIVE_DECODE_ERRORS` in a row; past that, or on a
+    // genuine I/O error, it reconnects and replays pending requests, then
+    // keeps reading on the new connection — a successful reconnect re-sends
+    // requests but delivers nothing by itself, so returning here would
+    // leave the caller's `recv()` blocked on a reply that was never read
+    // off the new stream.
+    pub fn get_next_message(&mut self) -> Result<u64, HalError> {
+        let mut decode_errors = 0;
+
+        loop {
+            match self.reader.read_frame::<FromDaemon>(&self.stream) {
+                Ok(Ok(message)) => {
+                    decode_errors = 0;
+                    let id = message.id();
+                    let listener = if self.subscriptions.contains(&id) {
+                        self.listeners.get(&id).map(|(_, sender)| sender.clone())
+                    } else {
+                        self.listeners.remove(&id).map(|(_, sender)| sender)
+                    };
+
+                    match listener {
+                        Some(listener) => {
+                            let _ = listener.send((*message.response()).clone());
+                            return Ok(id);
+                        }
+                        None => {
+                            // Most likely a reply for a req_id that
+                            // `get_next_message_with_timeout` already gave up
+                            // on and removed. It's not this call's reply, so
+                            // skip it and keep reading instead of treating it
+                            // as fatal and desyncing the next, unrelated call.
+                            error!("No listener registered for message #{}, skipping.", id);
+                        }
+                    }
+                }
+                Ok(Err(())) => {
+                    decode_errors += 1;
+                    error!(
+                        "Discarding a corrupt frame ({}/{} consecutive decode errors)",
+                        decode_errors, MAX_CONSECUTIVE_DECODE_ERRORS
+                    );
+                    if decode_errors >= MAX_CONSECUTIVE_DECODE_ERRORS {
+                        error!("Too many consecutive decode errors, attempting to reconnect.");
+                        self.reconnect()?;
+                        decode_errors = 0;
+                    }
+                }
+                Err(err) if is_timeout(&err) => return Err(HalError::Timeout),
+                Err(_) => {
+                    error!("Failed to read from the daemon, attempting to reconnect.");
+                    self.reconnect()?;
+                }
+            }
+        }
     }
 
-    // Blocks to get the next message, and dispatch it to the receiver.
-    pub fn get_next_message(&mut self) -> Result<(), HalError> {
-        let config = bincode::DefaultOptions::new().with_native_endian();
-        if let Ok(message) = config.deserialize_from::<_, FromDaemon>(&self.stream) {
-            if let Some(listener) = self.listeners.remove(&message.id()) {
-                let _ = listener.send((*message.response()).clone());
-            } else {
-                error!("No listener registered for message #{}", message.id());
-                return Err(HalError::NoListener);
+    // Like `get_next_message`, but gives up after `timeout` instead of
+    // blocking forever, and specifically waits for `req_id`'s reply rather
+    // than returning as soon as any message is dispatched — on a
+    // `HalClient` with more than one request in flight, an unrelated
+    // request's reply (or an event) can easily arrive first, and returning
+    // for it would leave `req_id`'s own caller blocked on `recv()` with no
+    // timeout protecting it anymore. On timeout, `req_id`'s listener is
+    // removed from `listeners` so an abandoned caller doesn't leak the slot.
+    pub fn get_next_message_with_timeout(
+        &mut self,
+        req_id: u64,
+        timeout: Duration,
+    ) -> Result<(), HalError> {
+        let deadline = Instant::now() + timeout;
+        self.active_deadline = Some(deadline);
+
+        let result = loop {
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            if remaining.is_zero() {
+                break Err(HalError::Timeout);
+            }
+
+            if self.stream.set_read_timeout(Some(remaining)).is_err() {
+                break Err(HalError::StreamError);
+            }
+            let result = self.get_next_message();
+            let _ = self.stream.set_read_timeout(None);
+
+            match result {
+                Ok(id) if id == req_id => break Ok(()),
+                Ok(_) => continue,
+                Err(HalError::Timeout) => break Err(HalError::Timeout),
+                Err(err) => break Err(err),
             }
-        } else {
-            error!("Failed to deserialize messages.");
-            return Err(HalError::StreamError);
+        };
+
+        self.active_deadline = None;
+        if let Err(HalError::Timeout) = result {
+            self.listeners.remove(&req_id);
+            self.subscriptions.remove(&req_id);
         }
+        result
+    }
+}
 
-        Ok(())
+// A multiplexed client: a dedicated reader thread owns the read half of the
+// `UnixStream` and dispatches every decoded `FromDaemon` to its registered
+// listener by `req_id`, so several requests can be in flight at once and
+// replies may come back out of order without stalling one another.
+pub struct AsyncHalClient {
+    stream: UnixStream,
+    req_id: Arc<Mutex<u64>>,
+    listeners: Arc<Mutex<HashMap<u64, Sender<Response>>>>,
+    // `req_id`s registered via `Request::Subscribe`, whose listener the
+    // reader thread keeps forwarding every event to instead of removing.
+    subscriptions: Arc<Mutex<HashSet<u64>>>,
+    // Populated from the daemon's `HelloAck` during `connect`, same as
+    // `HalClient::daemon_supports`.
+    daemon_supports: HashSet<RequestKind>,
+}
+
+impl AsyncHalClient {
+    pub fn connect(path: &str) -> Result<Self, io::Error> {
+        let stream = UnixStream::connect(path).map_err(|err| {
+            error!("Failed to connect to b2ghald at {}: {}", path, err);
+            err
+        })?;
+        let mut reader = FrameReader::new();
+        let daemon_supports = HalClient::handshake(&stream, &mut reader);
+        let reader_stream = stream.try_clone()?;
+        let listeners = Arc::new(Mutex::new(HashMap::new()));
+        let subscriptions = Arc::new(Mutex::new(HashSet::new()));
+        let reader_listeners = Arc::clone(&listeners);
+        let reader_subscriptions = Arc::clone(&subscriptions);
+
+        thread::spawn(move || {
+            Self::read_loop(reader_stream, reader, reader_listeners, reader_subscriptions)
+        });
+
+        Ok(Self {
+            stream,
+            req_id: Arc::new(Mutex::new(0)),
+            listeners,
+            subscriptions,
+            daemon_supports,
+        })
+    }
+
+    // Runs on the reader thread for the lifetime of the connection, decoding
+    // one `FromDaemon` message at a time and routing it to the listener
+    // registered for its `req_id`. A subscription's listener stays
+    // registered so it keeps receiving every event; any other listener is
+    // removed after its reply.
+    // A frame that fails to decode is discarded so the next frame can be
+    // read from where it left off, up to `MAX_CONSECUTIVE_DECODE_ERRORS` in
+    // a row; past that, or on a genuine I/O error, the reader thread gives
+    // up (reconnection is `HalClient`'s job, see chunk0-4) — but not before
+    // clearing `listeners`/`subscriptions` so every `Sender` still held is
+    // dropped. Otherwise a caller blocked on the matching `Receiver::recv()`
+    // would never unblock: with the thread gone, no reply is ever coming.
+    fn read_loop(
+        stream: UnixStream,
+        mut reader: FrameReader,
+        listeners: Arc<Mutex<HashMap<u64, Sender<Response>>>>,
+        subscriptions: Arc<Mutex<HashSet<u64>>>,
+    ) {
+        let mut decode_errors = 0;
+
+        loop {
+            match reader.read_frame::<FromDaemon>(&stream) {
+                Ok(Ok(message)) => {
+                    decode_errors = 0;
+                    let id = message.id();
+                    let mut listeners = listeners.lock().unwrap();
+                    let listener = if subscriptions.lock().unwrap().contains(&id) {
+                        listeners.get(&id).cloned()
+                    } else {
+                        listeners.remove(&id)
+                    };
+
+                    match listener {
+                        Some(listener) => {
+                            let _ = listener.send((*message.response()).clone());
+                        }
+                        None => error!("No listener registered for message #{}", id),
+                    }
+                }
+                Ok(Err(())) => {
+                    decode_errors += 1;
+                    error!(
+                        "Discarding a corrupt frame ({}/{} consecutive decode errors)",
+                        decode_errors, MAX_CONSECUTIVE_DECODE_ERRORS
+                    );
+                    if decode_errors >= MAX_CONSECUTIVE_DECODE_ERRORS {
+                        error!("Too many consecutive decode errors, reader thread exiting.");
+                        listeners.lock().unwrap().clear();
+                        subscriptions.lock().unwrap().clear();
+                        return;
+                    }
+                }
+                Err(_) => {
+                    error!("Failed to read from the daemon, reader thread exiting.");
+                    listeners.lock().unwrap().clear();
+                    subscriptions.lock().unwrap().clear();
+                    return;
+                }
+            }
+        }
+    }
+
+    // Subscribes to `kind` and returns a `Receiver` that yields every
+    // matching `Event` pushed by the daemon for as long as this client is
+    // connected.
+    pub fn subscribe(&mut self, kind: EventKind) -> Result<Receiver<Event>, HalError> {
+        let request = Request::Subscribe(kind);
+        if !self.daemon_supports.contains(&RequestKind::of(&request)) {
+            return Err(HalError::Unsupported);
+        }
+
+        let (sender, receiver) = channel();
+        let id = self.next_id();
+        self.listeners.lock().unwrap().insert(id, sender);
+        self.subscriptions.lock().unwrap().insert(id);
+
+        self.write(id, request).map_err(|_| HalError::StreamError)?;
+
+        Ok(receiver)
+    }
+
+    // Sends `request` and returns a `Receiver` the caller can block on
+    // whenever it's ready for the reply, without blocking other callers from
+    // issuing their own requests on this client in the meantime.
+    pub fn send(&mut self, request: Request) -> Result<Receiver<Response>, HalError> {
+        if !self.daemon_supports.contains(&RequestKind::of(&request)) {
+            return Err(HalError::Unsupported);
+        }
+
+        let (sender, receiver) = channel();
+        let id = self.next_id();
+        self.listeners.lock().unwrap().insert(id, sender);
+
+        self.write(id, request).map_err(|_| HalError::StreamError)?;
+
+        Ok(receiver)
+    }
+
+    fn next_id(&self) -> u64 {
+        let mut req_id = self.req_id.lock().unwrap();
+        let id = *req_id;
+        *req_id += 1;
+        id
+    }
+
+    fn write(&self, id: u64, request: Request) -> Result<(), io::Error> {
+        write_frame(&self.stream, &ToDaemon::new(id, request))
     }
 }
 
@@ -74,63 +698,85 @@ pub struct SimpleClient {
 }
 
 impl SimpleClient {
-    pub fn new() -> Option<Self> {
-        match HalClient::connect("/tmp/b2ghald.sock") {
+    pub fn new(policy: ReconnectPolicy) -> Option<Self> {
+        match HalClient::connect("/tmp/b2ghald.sock", policy) {
             Ok(client) => Some(Self { client }),
             Err(_) => None,
         }
     }
 
-    pub fn set_screen_brightness(&mut self, value: u8) {
+    pub fn set_screen_brightness(&mut self, value: u8, timeout: Duration) -> Result<(), HalError> {
         let (sender, receiver) = channel();
-        let _ = self.client.send(Request::SetBrightness(value), sender);
-        if self.client.get_next_message().is_ok() {
-            let _ = receiver.recv();
-        }
+        let id = self.client.send(Request::SetBrightness(value), sender)?;
+        self.client.get_next_message_with_timeout(id, timeout)?;
+        let _ = receiver.recv();
+        Ok(())
     }
 
-    pub fn get_screen_brightness(&mut self) -> u8 {
+    pub fn get_screen_brightness(&mut self, timeout: Duration) -> Result<u8, HalError> {
         let (sender, receiver) = channel();
-        let _ = self.client.send(Request::GetBrightness, sender);
-        if self.client.get_next_message().is_ok() {
-            match receiver.recv() {
-                Ok(Response::GetBrightnessSuccess(value)) => value,
-                Ok(_) | Err(_) => 0,
-            }
-        } else {
-            0
+        let id = self.client.send(Request::GetBrightness, sender)?;
+        self.client.get_next_message_with_timeout(id, timeout)?;
+        match receiver.recv() {
+            Ok(Response::GetBrightnessSuccess(value)) => Ok(value),
+            Ok(_) | Err(_) => Ok(0),
         }
     }
 
-    pub fn enable_screen(&mut self, screen_id: u8) {
+    pub fn enable_screen(&mut self, screen_id: u8, timeout: Duration) -> Result<(), HalError> {
         let (sender, receiver) = channel();
-        let _ = self.client.send(Request::EnableScreen(screen_id), sender);
-        if self.client.get_next_message().is_ok() {
-            let _ = receiver.recv();
-        }
+        let id = self.client.send(Request::EnableScreen(screen_id), sender)?;
+        self.client.get_next_message_with_timeout(id, timeout)?;
+        let _ = receiver.recv();
+        Ok(())
     }
 
-    pub fn disable_screen(&mut self, screen_id: u8) {
+    pub fn disable_screen(&mut self, screen_id: u8, timeout: Duration) -> Result<(), HalError> {
         let (sender, receiver) = channel();
-        let _ = self.client.send(Request::DisableScreen(screen_id), sender);
-        if self.client.get_next_message().is_ok() {
-            let _ = receiver.recv();
-        }
+        let id = self.client.send(Request::DisableScreen(screen_id), sender)?;
+        self.client.get_next_message_with_timeout(id, timeout)?;
+        let _ = receiver.recv();
+        Ok(())
     }
 
-    pub fn reboot(&mut self) {
+    pub fn reboot(&mut self, timeout: Duration) -> Result<(), HalError> {
         let (sender, receiver) = channel();
-        let _ = self.client.send(Request::Reboot, sender);
-        if self.client.get_next_message().is_ok() {
-            let _ = receiver.recv();
-        }
+        let id = self.client.send(Request::Reboot, sender)?;
+        self.client.get_next_message_with_timeout(id, timeout)?;
+        let _ = receiver.recv();
+        Ok(())
     }
 
-    pub fn poweroff(&mut self) {
+    pub fn poweroff(&mut self, timeout: Duration) -> Result<(), HalError> {
         let (sender, receiver) = channel();
-        let _ = self.client.send(Request::PowerOff, sender);
-        if self.client.get_next_message().is_ok() {
-            let _ = receiver.recv();
-        }
+        let id = self.client.send(Request::PowerOff, sender)?;
+        self.client.get_next_message_with_timeout(id, timeout)?;
+        let _ = receiver.recv();
+        Ok(())
+    }
+
+    // Subscribes to `kind` and returns a `Receiver` immediately, without
+    // waiting for any event to arrive: registering a subscription is just
+    // bookkeeping, and the daemon may not push a first `Event` for an
+    // arbitrary amount of time, if ever. Call `pump_events` whenever you're
+    // ready to block for the next one.
+    //
+    // Every message is dispatched to the listener registered for its own
+    // `req_id`, so RPC calls such as `set_screen_brightness` can still be
+    // made on this client while subscribed: an event that arrives while an
+    // RPC call is waiting for its reply goes to the subscription's
+    // `Receiver`, not to the RPC call. Events are only read off the stream
+    // during an RPC call or a `pump_events` call.
+    pub fn subscribe(&mut self, kind: EventKind) -> Result<Receiver<Event>, HalError> {
+        let (sender, receiver) = channel();
+        self.client.send(Request::Subscribe(kind), sender)?;
+        Ok(receiver)
+    }
+
+    // Blocks for the next message on an active subscription (or reply) and
+    // dispatches it to its registered listener.
+    pub fn pump_events(&mut self) -> Result<(), HalError> {
+        self.client.get_next_message()?;
+        Ok(())
     }
 }